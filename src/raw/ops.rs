@@ -1,10 +1,11 @@
 use std::cmp;
-use std::collections::BinaryHeap;
 
 use raw::Output;
-use Stream;
+use {Automaton, Stream};
 
 type BoxedStream<'f> = Box<for<'a> Stream<'a, Item=(&'a [u8], Output)> + 'f>;
+type BoxedMergedStream<'f> =
+    Box<for<'a> Stream<'a, Item=(&'a [u8], &'a [FstOutput])> + 'f>;
 
 #[derive(Copy, Clone, Debug)]
 pub struct FstOutput {
@@ -43,6 +44,306 @@ impl<'f> StreamOp<'f> {
             cur_slot: None,
         }
     }
+
+    pub fn difference(self) -> StreamDifference<'f> {
+        StreamDifference {
+            heap: StreamHeap::new(self.streams),
+            outs: vec![],
+            cur_slot: None,
+        }
+    }
+
+    pub fn symmetric_difference(self) -> StreamSymmetricDifference<'f> {
+        StreamSymmetricDifference {
+            heap: StreamHeap::new(self.streams),
+            outs: vec![],
+            cur_slot: None,
+        }
+    }
+
+    pub fn union_with<F>(self, f: F) -> StreamUnionWith<'f, F>
+            where F: FnMut(&[u8], &[FstOutput]) -> u64 {
+        StreamUnionWith { union: self.union(), f: f }
+    }
+
+    pub fn intersection_with<F>(self, f: F) -> StreamIntersectionWith<'f, F>
+            where F: FnMut(&[u8], &[FstOutput]) -> u64 {
+        StreamIntersectionWith { intersection: self.intersection(), f: f }
+    }
+
+    pub fn filtered<A: Automaton>(self, aut: A) -> StreamFiltered<'f, A> {
+        let start = aut.start();
+        StreamFiltered {
+            heap: StreamHeap::new(self.streams),
+            outs: vec![],
+            cur_slot: None,
+            aut: aut,
+            states: vec![start],
+            prev_key: vec![],
+        }
+    }
+}
+
+pub mod reducer {
+    use super::FstOutput;
+
+    pub fn sum(_key: &[u8], outs: &[FstOutput]) -> u64 {
+        outs.iter().fold(0, |a, o| a + o.output)
+    }
+
+    pub fn min(_key: &[u8], outs: &[FstOutput]) -> u64 {
+        outs.iter().map(|o| o.output).min().unwrap()
+    }
+
+    pub fn max(_key: &[u8], outs: &[FstOutput]) -> u64 {
+        outs.iter().map(|o| o.output).max().unwrap()
+    }
+
+    pub fn first(_key: &[u8], outs: &[FstOutput]) -> u64 {
+        outs.iter().min_by_key(|o| o.index).unwrap().output
+    }
+
+    pub fn last(_key: &[u8], outs: &[FstOutput]) -> u64 {
+        outs.iter().max_by_key(|o| o.index).unwrap().output
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum GroupKind {
+    Positive,
+    Negative,
+    Delete,
+}
+
+pub struct SignedStreamOp<'f> {
+    groups: Vec<(GroupKind, Vec<BoxedStream<'f>>)>,
+}
+
+impl<'f> SignedStreamOp<'f> {
+    pub fn new() -> Self {
+        SignedStreamOp { groups: vec![] }
+    }
+
+    pub fn add_positive<S>(self, stream: S) -> Self
+            where S: 'f + for<'a> Stream<'a, Item=(&'a [u8], Output)> {
+        self.push(GroupKind::Positive, Box::new(stream))
+    }
+
+    pub fn add_negative<S>(self, stream: S) -> Self
+            where S: 'f + for<'a> Stream<'a, Item=(&'a [u8], Output)> {
+        self.push(GroupKind::Negative, Box::new(stream))
+    }
+
+    pub fn add_deleted<S>(self, stream: S) -> Self
+            where S: 'f + for<'a> Stream<'a, Item=(&'a [u8], Output)> {
+        self.push(GroupKind::Delete, Box::new(stream))
+    }
+
+    fn push(mut self, kind: GroupKind, stream: BoxedStream<'f>) -> Self {
+        let same_group =
+            self.groups.last().map(|&(k, _)| k == kind).unwrap_or(false);
+        if same_group {
+            self.groups.last_mut().unwrap().1.push(stream);
+        } else {
+            self.groups.push((kind, vec![stream]));
+        }
+        self
+    }
+
+    pub fn merge_with<F>(self, mut f: F) -> SignedMerge
+            where F: FnMut(&[u8], &[FstOutput]) -> u64 {
+        let mut acc: Vec<(Vec<u8>, u64)> = vec![];
+        for (kind, streams) in self.groups {
+            let group_op = StreamOp { streams: streams };
+            if kind == GroupKind::Delete {
+                let mut group_stream = group_op.union();
+                let mut group_keys = vec![];
+                while let Some((key, _)) = group_stream.next() {
+                    group_keys.push(key.to_vec());
+                }
+                acc = merge_delete(acc, group_keys);
+                continue;
+            }
+            let mut group_stream = group_op.union_with(&mut f);
+            let mut group_items = vec![];
+            while let Some((key, output)) = group_stream.next() {
+                group_items.push((key.to_vec(), output.value()));
+            }
+            acc = match kind {
+                GroupKind::Positive => merge_additive(acc, group_items),
+                GroupKind::Negative => merge_subtractive(acc, group_items),
+                GroupKind::Delete => unreachable!(),
+            };
+        }
+        SignedMerge {
+            items: acc.into_iter()
+                      .map(|(k, v)| (k, FstOutput { index: 0, output: v }))
+                      .collect(),
+            outs: vec![],
+            pos: 0,
+        }
+    }
+}
+
+fn merge_additive(
+    acc: Vec<(Vec<u8>, u64)>,
+    group: Vec<(Vec<u8>, u64)>,
+) -> Vec<(Vec<u8>, u64)> {
+    let mut out = Vec::with_capacity(acc.len() + group.len());
+    let mut ai = acc.into_iter().peekable();
+    let mut gi = group.into_iter().peekable();
+    loop {
+        let ord = match (ai.peek(), gi.peek()) {
+            (None, None) => break,
+            (Some(_), None) => cmp::Ordering::Less,
+            (None, Some(_)) => cmp::Ordering::Greater,
+            (Some(a), Some(g)) => a.0.cmp(&g.0),
+        };
+        match ord {
+            cmp::Ordering::Less => out.push(ai.next().unwrap()),
+            cmp::Ordering::Greater => out.push(gi.next().unwrap()),
+            cmp::Ordering::Equal => {
+                let (k, av) = ai.next().unwrap();
+                let (_, gv) = gi.next().unwrap();
+                out.push((k, av + gv));
+            }
+        }
+    }
+    out
+}
+
+fn merge_subtractive(
+    acc: Vec<(Vec<u8>, u64)>,
+    group: Vec<(Vec<u8>, u64)>,
+) -> Vec<(Vec<u8>, u64)> {
+    let mut out = Vec::with_capacity(acc.len());
+    let mut gi = group.into_iter().peekable();
+    for (key, val) in acc {
+        while gi.peek().map(|g| g.0 < key).unwrap_or(false) {
+            gi.next();
+        }
+        match gi.peek() {
+            Some(g) if g.0 == key => {
+                let gv = gi.next().unwrap().1;
+                let remaining = val.saturating_sub(gv);
+                if remaining > 0 {
+                    out.push((key, remaining));
+                }
+            }
+            _ => out.push((key, val)),
+        }
+    }
+    out
+}
+
+fn merge_delete(
+    acc: Vec<(Vec<u8>, u64)>,
+    deleted_keys: Vec<Vec<u8>>,
+) -> Vec<(Vec<u8>, u64)> {
+    let mut out = Vec::with_capacity(acc.len());
+    let mut gi = deleted_keys.into_iter().peekable();
+    for (key, val) in acc {
+        while gi.peek().map(|g| *g < key).unwrap_or(false) {
+            gi.next();
+        }
+        match gi.peek() {
+            Some(g) if *g == key => {
+                gi.next();
+            }
+            _ => out.push((key, val)),
+        }
+    }
+    out
+}
+
+pub struct SignedMerge {
+    items: Vec<(Vec<u8>, FstOutput)>,
+    outs: Vec<FstOutput>,
+    pos: usize,
+}
+
+impl<'a> Stream<'a> for SignedMerge {
+    type Item = (&'a [u8], &'a [FstOutput]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if self.pos >= self.items.len() {
+            return None;
+        }
+        let (ref key, output) = self.items[self.pos];
+        self.pos += 1;
+        self.outs.clear();
+        self.outs.push(output);
+        Some((key, &self.outs))
+    }
+}
+
+pub struct StreamFiltered<'f, A: Automaton> {
+    heap: StreamHeap<'f>,
+    outs: Vec<FstOutput>,
+    cur_slot: Option<Slot>,
+    aut: A,
+    states: Vec<A::State>,
+    prev_key: Vec<u8>,
+}
+
+impl<'f, A: Automaton> StreamFiltered<'f, A> {
+    // `states[i]` is the automaton state after consuming the first `i`
+    // bytes of `prev_key`, so a key sharing a prefix with `prev_key` only
+    // needs its divergent suffix run through `accept`/`can_match` instead
+    // of restarting from `aut.start()` every time.
+    fn accepts(&mut self, key: &[u8]) -> bool {
+        let shared = self.prev_key.iter().zip(key.iter())
+            .take_while(|&(a, b)| a == b)
+            .count();
+        let common = cmp::min(shared, self.states.len() - 1);
+        self.states.truncate(common + 1);
+        let mut matched = true;
+        for &byte in &key[common..] {
+            if !self.aut.can_match(self.states.last().unwrap()) {
+                matched = false;
+                break;
+            }
+            let next = self.aut.accept(self.states.last().unwrap(), byte);
+            self.states.push(next);
+        }
+        self.prev_key.clear();
+        self.prev_key.extend(key);
+        matched && self.aut.is_match(self.states.last().unwrap())
+    }
+}
+
+impl<'a, 'f, A: Automaton> Stream<'a> for StreamFiltered<'f, A> {
+    type Item = (&'a [u8], &'a [FstOutput]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if let Some(slot) = self.cur_slot.take() {
+            self.heap.refill(slot);
+        }
+        loop {
+            let slot = match self.heap.pop() {
+                None => return None,
+                Some(slot) => slot,
+            };
+            let matched = self.accepts(slot.input());
+            if matched {
+                self.outs.clear();
+                self.outs.push(slot.fst_output());
+            }
+            while let Some(slot2) = self.heap.pop_if_equal(slot.input()) {
+                if matched {
+                    self.outs.push(slot2.fst_output());
+                }
+                self.heap.refill(slot2);
+            }
+            if matched {
+                self.cur_slot = Some(slot);
+                let key = self.cur_slot.as_ref().unwrap().input();
+                return Some((key, &self.outs));
+            } else {
+                self.heap.refill(slot);
+            }
+        }
+    }
 }
 
 pub struct StreamUnion<'f> {
@@ -112,29 +413,263 @@ impl<'a, 'f> Stream<'a> for StreamIntersection<'f> {
     }
 }
 
+pub struct StreamUnionWith<'f, F> {
+    union: StreamUnion<'f>,
+    f: F,
+}
+
+impl<'a, 'f, F> Stream<'a> for StreamUnionWith<'f, F>
+        where F: FnMut(&[u8], &[FstOutput]) -> u64 {
+    type Item = (&'a [u8], Output);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self.union.next() {
+            None => None,
+            Some((key, outs)) => {
+                let merged = (self.f)(key, outs);
+                Some((key, Output::new(merged)))
+            }
+        }
+    }
+}
+
+pub struct StreamIntersectionWith<'f, F> {
+    intersection: StreamIntersection<'f>,
+    f: F,
+}
+
+impl<'a, 'f, F> Stream<'a> for StreamIntersectionWith<'f, F>
+        where F: FnMut(&[u8], &[FstOutput]) -> u64 {
+    type Item = (&'a [u8], Output);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self.intersection.next() {
+            None => None,
+            Some((key, outs)) => {
+                let merged = (self.f)(key, outs);
+                Some((key, Output::new(merged)))
+            }
+        }
+    }
+}
+
+pub struct StreamDifference<'f> {
+    heap: StreamHeap<'f>,
+    outs: Vec<FstOutput>,
+    cur_slot: Option<Slot>,
+}
+
+impl<'a, 'f> Stream<'a> for StreamDifference<'f> {
+    type Item = (&'a [u8], &'a [FstOutput]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if let Some(slot) = self.cur_slot.take() {
+            self.heap.refill(slot);
+        }
+        loop {
+            let slot = match self.heap.pop() {
+                None => return None,
+                Some(slot) => slot,
+            };
+            self.outs.clear();
+            self.outs.push(slot.fst_output());
+            let mut only_base = slot.idx == 0;
+            while let Some(slot2) = self.heap.pop_if_equal(slot.input()) {
+                only_base = false;
+                self.outs.push(slot2.fst_output());
+                self.heap.refill(slot2);
+            }
+            if only_base {
+                self.cur_slot = Some(slot);
+                let key = self.cur_slot.as_ref().unwrap().input();
+                return Some((key, &self.outs));
+            } else {
+                self.heap.refill(slot);
+            }
+        }
+    }
+}
+
+pub struct StreamSymmetricDifference<'f> {
+    heap: StreamHeap<'f>,
+    outs: Vec<FstOutput>,
+    cur_slot: Option<Slot>,
+}
+
+impl<'a, 'f> Stream<'a> for StreamSymmetricDifference<'f> {
+    type Item = (&'a [u8], &'a [FstOutput]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if let Some(slot) = self.cur_slot.take() {
+            self.heap.refill(slot);
+        }
+        loop {
+            let slot = match self.heap.pop() {
+                None => return None,
+                Some(slot) => slot,
+            };
+            self.outs.clear();
+            self.outs.push(slot.fst_output());
+            let mut popped: usize = 1;
+            while let Some(slot2) = self.heap.pop_if_equal(slot.input()) {
+                self.outs.push(slot2.fst_output());
+                self.heap.refill(slot2);
+                popped += 1;
+            }
+            if popped % 2 == 1 {
+                self.cur_slot = Some(slot);
+                let key = self.cur_slot.as_ref().unwrap().input();
+                return Some((key, &self.outs));
+            } else {
+                self.heap.refill(slot);
+            }
+        }
+    }
+}
+
+// These adaptors can't live as default methods on `Stream` itself: that
+// trait is defined outside this module/crate, so `map`/`filter`/
+// `filter_map` are bolted on here via an extension trait instead, and
+// only for the merged-stream shape `(&'a [u8], &'a [FstOutput])` that
+// StreamOp/SignedStreamOp produce. A `StreamMap`'s own output isn't
+// `(&'a [u8], &'a [FstOutput])` in general, so it can't be re-adapted
+// through this same trait; chain `map`/`filter`/`filter_map` before
+// converting to a concrete `T`, not after.
+pub trait StreamExt<'f>: 'f + for<'a> Stream<'a, Item=(&'a [u8], &'a [FstOutput])> + Sized {
+    fn map<F, T>(self, f: F) -> StreamMap<'f, T>
+            where F: 'f + FnMut(&[u8], &[FstOutput]) -> T {
+        StreamMap { stream: Box::new(self), f: Box::new(f) }
+    }
+
+    fn filter<F>(self, pred: F) -> StreamFilter<'f>
+            where F: 'f + FnMut(&[u8], &[FstOutput]) -> bool {
+        StreamFilter {
+            stream: Box::new(self),
+            pred: Box::new(pred),
+            key: vec![],
+            outs: vec![],
+        }
+    }
+
+    fn filter_map<F, T>(self, f: F) -> StreamFilterMap<'f, T>
+            where F: 'f + FnMut(&[u8], &[FstOutput]) -> Option<T> {
+        StreamFilterMap { stream: Box::new(self), f: Box::new(f), key: vec![] }
+    }
+}
+
+impl<'f, S> StreamExt<'f> for S
+        where S: 'f + for<'a> Stream<'a, Item=(&'a [u8], &'a [FstOutput])> {}
+
+pub struct StreamMap<'f, T> {
+    stream: BoxedMergedStream<'f>,
+    f: Box<FnMut(&[u8], &[FstOutput]) -> T + 'f>,
+}
+
+impl<'a, 'f, T> Stream<'a> for StreamMap<'f, T> {
+    type Item = (&'a [u8], T);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self.stream.next() {
+            None => None,
+            Some((key, outs)) => {
+                let t = (self.f)(key, outs);
+                Some((key, t))
+            }
+        }
+    }
+}
+
+pub struct StreamFilter<'f> {
+    stream: BoxedMergedStream<'f>,
+    pred: Box<FnMut(&[u8], &[FstOutput]) -> bool + 'f>,
+    key: Vec<u8>,
+    outs: Vec<FstOutput>,
+}
+
+impl<'a, 'f> Stream<'a> for StreamFilter<'f> {
+    type Item = (&'a [u8], &'a [FstOutput]);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        loop {
+            match self.stream.next() {
+                None => return None,
+                Some((key, outs)) => {
+                    if (self.pred)(key, outs) {
+                        self.key.clear();
+                        self.key.extend(key);
+                        self.outs.clear();
+                        self.outs.extend(outs);
+                        return Some((&self.key, &self.outs));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct StreamFilterMap<'f, T> {
+    stream: BoxedMergedStream<'f>,
+    f: Box<FnMut(&[u8], &[FstOutput]) -> Option<T> + 'f>,
+    key: Vec<u8>,
+}
+
+impl<'a, 'f, T> Stream<'a> for StreamFilterMap<'f, T> {
+    type Item = (&'a [u8], T);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        loop {
+            match self.stream.next() {
+                None => return None,
+                Some((key, outs)) => {
+                    if let Some(t) = (self.f)(key, outs) {
+                        self.key.clear();
+                        self.key.extend(key);
+                        return Some((&self.key, t));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Each stream keeps one persistent Slot (its Vec<u8> key buffer is
+// reused via clear()+extend() and only grows when a key is longer than
+// its current capacity), so refilling a stream
+// no longer allocates a fresh node the way BinaryHeap<Slot> did. Refill
+// itself is still remove-root-then-reinsert (two O(log n) sifts), not an
+// in-place decrease-key over a fixed-size array.
 struct StreamHeap<'f> {
     rdrs: Vec<Box<for<'a> Stream<'a, Item=(&'a [u8], Output)> + 'f>>,
-    heap: BinaryHeap<Slot>,
+    slots: Vec<Option<Slot>>,
+    heap: Vec<usize>,
 }
 
 impl<'f> StreamHeap<'f> {
     fn new(streams: Vec<BoxedStream<'f>>) -> StreamHeap<'f> {
+        let n = streams.len();
         let mut u = StreamHeap {
             rdrs: streams,
-            heap: BinaryHeap::new(),
+            slots: (0..n).map(|i| Some(Slot::new(i))).collect(),
+            heap: Vec::with_capacity(n),
         };
-        for i in 0..u.rdrs.len() {
-            u.refill(Slot::new(i));
+        for i in 0..n {
+            u.advance(i);
         }
         u
     }
 
     fn pop(&mut self) -> Option<Slot> {
-        self.heap.pop()
+        if self.heap.is_empty() {
+            return None;
+        }
+        let idx = self.remove_root();
+        self.slots[idx].take()
     }
 
     fn peek_is_duplicate(&self, key: &[u8]) -> bool {
-        self.heap.peek().map(|s| s.input() == key).unwrap_or(false)
+        self.heap.first()
+            .map(|&idx| self.slots[idx].as_ref().unwrap().input() == key)
+            .unwrap_or(false)
     }
 
     fn pop_if_equal(&mut self, key: &[u8]) -> Option<Slot> {
@@ -149,16 +684,86 @@ impl<'f> StreamHeap<'f> {
         self.rdrs.len()
     }
 
-    fn refill(&mut self, mut slot: Slot) {
-        if let Some((input, output)) = self.rdrs[slot.idx].next() {
-            slot.set_input(input);
-            slot.set_output(output);
-            self.heap.push(slot);
+    fn refill(&mut self, slot: Slot) {
+        let idx = slot.idx;
+        self.slots[idx] = Some(slot);
+        self.advance(idx);
+    }
+
+    fn advance(&mut self, idx: usize) {
+        if let Some((input, output)) = self.rdrs[idx].next() {
+            {
+                let slot = self.slots[idx].as_mut().unwrap();
+                slot.set_input(input);
+                slot.set_output(output);
+            }
+            self.insert(idx);
+        }
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        let sa = self.slots[a].as_ref().unwrap();
+        let sb = self.slots[b].as_ref().unwrap();
+        (&sa.input, sa.output) < (&sb.input, sb.output)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+    }
+
+    fn insert(&mut self, idx: usize) {
+        let i = self.heap.len();
+        self.heap.push(idx);
+        self.sift_up(i);
+    }
+
+    fn remove_root(&mut self) -> usize {
+        let idx = self.heap[0];
+        let last = self.heap.len() - 1;
+        if last > 0 {
+            self.heap[0] = self.heap[last];
+        }
+        self.heap.pop();
+        if !self.heap.is_empty() {
+            self.sift_down(0);
+        }
+        idx
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.less(self.heap[i], self.heap[parent]) {
+                self.swap(i, parent);
+                i = parent;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        let len = self.heap.len();
+        loop {
+            let l = 2 * i + 1;
+            let r = 2 * i + 2;
+            let mut smallest = i;
+            if l < len && self.less(self.heap[l], self.heap[smallest]) {
+                smallest = l;
+            }
+            if r < len && self.less(self.heap[r], self.heap[smallest]) {
+                smallest = r;
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug)]
 struct Slot {
     idx: usize,
     input: Vec<u8>,
@@ -187,7 +792,6 @@ impl Slot {
     }
 
     fn set_input(&mut self, input: &[u8]) {
-        let addcap = input.len().checked_sub(self.input.len()).unwrap_or(0);
         self.input.clear();
         self.input.extend(input);
     }
@@ -197,31 +801,42 @@ impl Slot {
     }
 }
 
-impl PartialOrd for Slot {
-    fn partial_cmp(&self, other: &Slot) -> Option<cmp::Ordering> {
-        (&self.input, self.output)
-        .partial_cmp(&(&other.input, other.output))
-        .map(|ord| ord.reverse())
-    }
-}
-
-impl Ord for Slot {
-    fn cmp(&self, other: &Slot) -> cmp::Ordering {
-        self.partial_cmp(other).unwrap()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use raw::build::Builder;
     use raw::tests::{fst_map, fst_set, fst_inputstrs_outputs, fst_input_strs};
     use raw::Fst;
-    use {Result, Stream};
+    use {Automaton, Result, Stream};
 
-    use super::{StreamOp, FstOutput};
+    use super::{StreamOp, SignedStreamOp, FstOutput, StreamExt, reducer};
 
     fn s(string: &str) -> String { string.to_owned() }
 
+    struct StartsWith(Vec<u8>);
+
+    impl Automaton for StartsWith {
+        type State = Option<usize>;
+
+        fn start(&self) -> Option<usize> { Some(0) }
+
+        fn is_match(&self, state: &Option<usize>) -> bool {
+            state.map(|pos| pos >= self.0.len()).unwrap_or(false)
+        }
+
+        fn can_match(&self, state: &Option<usize>) -> bool {
+            state.is_some()
+        }
+
+        fn accept(&self, state: &Option<usize>, byte: u8) -> Option<usize> {
+            match *state {
+                None => None,
+                Some(pos) if pos >= self.0.len() => Some(pos),
+                Some(pos) if self.0[pos] == byte => Some(pos + 1),
+                Some(_) => None,
+            }
+        }
+    }
+
     fn stream_to_set<I>(mut stream: I) -> Result<Fst>
             where I: for<'a> Stream<'a, Item=(&'a [u8], &'a [FstOutput])> {
         let mut bfst = Builder::memory();
@@ -241,6 +856,15 @@ mod tests {
         Ok(try!(Fst::from_bytes(try!(bfst.into_inner()))))
     }
 
+    fn stream_with_to_map<I>(mut stream: I) -> Result<Fst>
+            where I: for<'a> Stream<'a, Item=(&'a [u8], ::raw::Output)> {
+        let mut bfst = Builder::memory();
+        while let Some((key, output)) = stream.next() {
+            try!(bfst.insert(key, output.value()));
+        }
+        Ok(try!(Fst::from_bytes(try!(bfst.into_inner()))))
+    }
+
     #[test]
     fn union_set() {
         let set1 = fst_set(&["a", "b", "c"]);
@@ -356,4 +980,266 @@ mod tests {
         let inter_stream = stream_to_map(op).unwrap();
         assert_eq!(fst_inputstrs_outputs(&inter_stream), vec![(s("b"), 4)]);
     }
+
+    #[test]
+    fn difference_set_dupes() {
+        let sets = &[
+            fst_set(&["aa", "b", "cc"]),
+            fst_set(&["b", "cc", "z"]),
+        ];
+        let op = StreamOp::new()
+                              .add(sets[0].stream()).add(sets[1].stream())
+                              .difference();
+        let diff_stream = stream_to_set(op).unwrap();
+        assert_eq!(fst_input_strs(&diff_stream), vec!["aa"]);
+    }
+
+    #[test]
+    fn difference_map_dupes() {
+        let maps = &[
+            fst_map(vec![("aa", 1), ("b", 2), ("cc", 3)]),
+            fst_map(vec![("b", 1), ("cc", 2), ("z", 3)]),
+            fst_map(vec![("b", 1)]),
+        ];
+        let op = StreamOp::new()
+                              .add(maps[0].stream())
+                              .add(maps[1].stream())
+                              .add(maps[2].stream())
+                              .difference();
+        let diff_stream = stream_to_map(op).unwrap();
+        assert_eq!(fst_inputstrs_outputs(&diff_stream), vec![(s("aa"), 1)]);
+    }
+
+    #[test]
+    fn symmetric_difference_set_dupes() {
+        let sets = &[
+            fst_set(&["aa", "b", "cc"]),
+            fst_set(&["b", "cc", "z"]),
+        ];
+        let op = StreamOp::new()
+                              .add(sets[0].stream()).add(sets[1].stream())
+                              .symmetric_difference();
+        let symdiff_stream = stream_to_set(op).unwrap();
+        assert_eq!(fst_input_strs(&symdiff_stream), vec!["aa", "z"]);
+    }
+
+    #[test]
+    fn symmetric_difference_map_dupes() {
+        let maps = &[
+            fst_map(vec![("aa", 1), ("b", 2), ("cc", 3)]),
+            fst_map(vec![("b", 1), ("cc", 2), ("z", 3)]),
+            fst_map(vec![("b", 1)]),
+        ];
+        let op = StreamOp::new()
+                              .add(maps[0].stream())
+                              .add(maps[1].stream())
+                              .add(maps[2].stream())
+                              .symmetric_difference();
+        let symdiff_stream = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&symdiff_stream),
+            vec![(s("aa"), 1), (s("b"), 4), (s("z"), 3)]);
+    }
+
+    #[test]
+    fn union_with_sum() {
+        let map1 = fst_map(vec![("aa", 1), ("b", 2), ("cc", 3)]);
+        let map2 = fst_map(vec![("b", 1), ("cc", 2), ("z", 3)]);
+        let map3 = fst_map(vec![("b", 1)]);
+
+        let op = StreamOp::new()
+                              .add(map1.stream())
+                              .add(map2.stream())
+                              .add(map3.stream())
+                              .union_with(reducer::sum);
+        let union = stream_with_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&union),
+            vec![(s("aa"), 1), (s("b"), 4), (s("cc"), 5), (s("z"), 3)]);
+    }
+
+    #[test]
+    fn union_with_first_and_last_are_stream_order() {
+        let map1 = fst_map(vec![("b", 5)]);
+        let map2 = fst_map(vec![("b", 9)]);
+        let map3 = fst_map(vec![("b", 2)]);
+
+        let first_op = StreamOp::new()
+                              .add(map1.stream())
+                              .add(map2.stream())
+                              .add(map3.stream())
+                              .union_with(reducer::first);
+        let first = stream_with_to_map(first_op).unwrap();
+        assert_eq!(fst_inputstrs_outputs(&first), vec![(s("b"), 5)]);
+
+        let last_op = StreamOp::new()
+                              .add(map1.stream())
+                              .add(map2.stream())
+                              .add(map3.stream())
+                              .union_with(reducer::last);
+        let last = stream_with_to_map(last_op).unwrap();
+        assert_eq!(fst_inputstrs_outputs(&last), vec![(s("b"), 2)]);
+    }
+
+    #[test]
+    fn intersection_with_max() {
+        let maps = &[
+            fst_map(vec![("aa", 1), ("b", 2), ("cc", 3)]),
+            fst_map(vec![("b", 1), ("cc", 2), ("z", 3)]),
+            fst_map(vec![("b", 5)]),
+        ];
+        let op = StreamOp::new()
+                              .add(maps[0].stream())
+                              .add(maps[1].stream())
+                              .add(maps[2].stream())
+                              .intersection_with(reducer::max);
+        let inter = stream_with_to_map(op).unwrap();
+        assert_eq!(fst_inputstrs_outputs(&inter), vec![(s("b"), 5)]);
+    }
+
+    #[test]
+    fn signed_merge_set() {
+        let base = fst_set(&["a", "b", "c"]);
+        let removed = fst_set(&["b"]);
+
+        let op = SignedStreamOp::new()
+                              .add_positive(base.stream())
+                              .add_deleted(removed.stream())
+                              .merge_with(reducer::sum);
+        let merged = stream_to_set(op).unwrap();
+        assert_eq!(fst_input_strs(&merged), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn signed_merge_map() {
+        let base = fst_map(vec![("a", 1), ("b", 5), ("c", 3)]);
+        let delta = fst_map(vec![("b", 2), ("c", 3), ("d", 7)]);
+
+        let op = SignedStreamOp::new()
+                              .add_positive(base.stream())
+                              .add_negative(delta.stream())
+                              .merge_with(reducer::sum);
+        let merged = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&merged),
+            vec![(s("a"), 1), (s("b"), 3)]);
+    }
+
+    #[test]
+    fn signed_merge_map_minus_set() {
+        let base = fst_map(vec![("a", 1), ("b", 5), ("c", 3)]);
+        let deleted = fst_set(&["b"]);
+
+        let op = SignedStreamOp::new()
+                              .add_positive(base.stream())
+                              .add_deleted(deleted.stream())
+                              .merge_with(reducer::sum);
+        let merged = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&merged),
+            vec![(s("a"), 1), (s("c"), 3)]);
+    }
+
+    #[test]
+    fn signed_merge_negative_zero_value_is_a_noop() {
+        let base = fst_map(vec![("a", 1), ("b", 5)]);
+        let delta = fst_map(vec![("b", 0)]);
+
+        let op = SignedStreamOp::new()
+                              .add_positive(base.stream())
+                              .add_negative(delta.stream())
+                              .merge_with(reducer::sum);
+        let merged = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&merged),
+            vec![(s("a"), 1), (s("b"), 5)]);
+    }
+
+    #[test]
+    fn signed_merge_reintroduces_after_delete() {
+        let base = fst_map(vec![("a", 1), ("b", 2)]);
+        let delete = fst_map(vec![("a", 1)]);
+        let restore = fst_map(vec![("a", 4)]);
+
+        let op = SignedStreamOp::new()
+                              .add_positive(base.stream())
+                              .add_negative(delete.stream())
+                              .add_positive(restore.stream())
+                              .merge_with(reducer::sum);
+        let merged = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&merged),
+            vec![(s("a"), 4), (s("b"), 2)]);
+    }
+
+    #[test]
+    fn map_adaptor() {
+        let map1 = fst_map(vec![("a", 1), ("b", 2)]);
+        let map2 = fst_map(vec![("b", 3), ("c", 4)]);
+
+        let op = StreamOp::new().add(map1.stream()).add(map2.stream()).union();
+        let mut stream = op.map(|_, outs| outs.len());
+        let mut got = vec![];
+        while let Some((key, n)) = stream.next() {
+            got.push((String::from_utf8(key.to_vec()).unwrap(), n));
+        }
+        assert_eq!(got, vec![(s("a"), 1), (s("b"), 2), (s("c"), 1)]);
+    }
+
+    #[test]
+    fn filter_adaptor_keeps_dupes() {
+        let map1 = fst_map(vec![("a", 1), ("b", 2)]);
+        let map2 = fst_map(vec![("b", 3), ("c", 4)]);
+
+        let op = StreamOp::new().add(map1.stream()).add(map2.stream()).union();
+        let mut stream = op.filter(|_, outs| outs.len() > 1);
+        let mut got = vec![];
+        while let Some((key, _)) = stream.next() {
+            got.push(String::from_utf8(key.to_vec()).unwrap());
+        }
+        assert_eq!(got, vec![s("b")]);
+    }
+
+    #[test]
+    fn filter_map_adaptor_threshold() {
+        let map1 = fst_map(vec![("a", 1), ("b", 5), ("c", 2)]);
+        let map2 = fst_map(vec![("b", 1), ("c", 4), ("d", 9)]);
+
+        let op = StreamOp::new().add(map1.stream()).add(map2.stream()).union();
+        let mut stream = op.filter_map(|_, outs| {
+            let sum = outs.iter().fold(0, |a, o| a + o.output);
+            if sum > 3 { Some(sum) } else { None }
+        });
+        let mut got = vec![];
+        while let Some((key, sum)) = stream.next() {
+            got.push((String::from_utf8(key.to_vec()).unwrap(), sum));
+        }
+        assert_eq!(got, vec![(s("b"), 6), (s("c"), 6), (s("d"), 9)]);
+    }
+
+    #[test]
+    fn filtered_set() {
+        let set1 = fst_set(&["aa", "ab", "ba"]);
+        let set2 = fst_set(&["ac", "bb"]);
+
+        let op = StreamOp::new()
+                              .add(set1.stream()).add(set2.stream())
+                              .filtered(StartsWith(b"a".to_vec()));
+        let matched = stream_to_set(op).unwrap();
+        assert_eq!(fst_input_strs(&matched), vec!["aa", "ab", "ac"]);
+    }
+
+    #[test]
+    fn filtered_map_dupes() {
+        let map1 = fst_map(vec![("aa", 1), ("ab", 2), ("ba", 3)]);
+        let map2 = fst_map(vec![("aa", 4), ("bb", 5)]);
+
+        let op = StreamOp::new()
+                              .add(map1.stream()).add(map2.stream())
+                              .filtered(StartsWith(b"a".to_vec()));
+        let matched = stream_to_map(op).unwrap();
+        assert_eq!(
+            fst_inputstrs_outputs(&matched),
+            vec![(s("aa"), 5), (s("ab"), 2)]);
+    }
 }